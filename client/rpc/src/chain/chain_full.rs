@@ -21,22 +21,32 @@
 use super::{
 	client_err,
 	error::{Error, FutureResult, Result},
-	BlockStats, ChainBackend,
+	BlockStats, ChainBackend, Compression, HeadFilter,
 };
-use futures::FutureExt;
-use jsonrpc_pubsub::manager::SubscriptionManager;
+use futures::{compat::Stream03CompatExt, future, FutureExt, StreamExt};
+use jsonrpc_pubsub::{manager::SubscriptionManager, typed::Subscriber};
+use log::warn;
+use rpc::futures::{Future as _, Sink as _};
 use sc_client_api::{BlockBackend, BlockchainEvents};
 use sp_api::{ApiExt, Core, ProvideRuntimeApi};
 use sp_blockchain::HeaderBackend;
 use sp_core::Encode;
 use sp_runtime::{
 	generic::{BlockId, SignedBlock},
-	traits::{Block as BlockT, Header},
+	traits::{Block as BlockT, Header, NumberFor, One, UniqueSaturatedInto},
 };
 use std::{marker::PhantomData, sync::Arc};
 
+use jsonrpc_core as rpc;
+
 type HasherOf<Block> = <<Block as BlockT>::Header as Header>::Hashing;
 
+/// Maximum number of blocks a single `block_stats_range` call may cover.
+///
+/// Each block in the range is re-executed with proof recording, so this bounds
+/// the inline work a single RPC call can schedule on a worker thread.
+const MAX_BLOCK_STATS_RANGE: u64 = 500;
+
 /// Blockchain API backend for full nodes. Reads all the data from local database.
 pub struct FullChain<Block: BlockT, Client> {
 	/// Substrate client.
@@ -83,36 +93,220 @@ where
 		async move { res }.boxed()
 	}
 
-	fn block_stats(&self, hash: Option<Block::Hash>) -> Result<Option<BlockStats>> {
-		let block = {
-			let block = self
-				.client
-				.block(&BlockId::Hash(self.unwrap_or_best(hash)))
-				.map_err(client_err)?;
-			if let Some(block) = block {
-				block.block
-			} else {
-				return Ok(None)
+	fn block_stats(
+		&self,
+		hash: Option<Block::Hash>,
+		compression: Option<Vec<Compression>>,
+	) -> Result<Option<BlockStats>> {
+		let compression = compression.unwrap_or_else(Compression::defaults);
+		Self::stats_for_block(&self.client, self.unwrap_or_best(hash), &compression)
+	}
+
+	fn block_stats_range(
+		&self,
+		from: NumberFor<Block>,
+		to: NumberFor<Block>,
+		compression: Option<Vec<Compression>>,
+	) -> Result<Vec<(Block::Hash, BlockStats)>> {
+		if to < from {
+			return Err(Error::Other(format!("Invalid block range: {:?}..={:?}", from, to)))
+		}
+		// Each block in the range is executed inline with proof recording, so a
+		// wide span would block the worker thread re-executing thousands of
+		// blocks. Reject spans larger than `MAX_BLOCK_STATS_RANGE`.
+		let span: u64 = (to - from).unique_saturated_into();
+		if span >= MAX_BLOCK_STATS_RANGE {
+			return Err(Error::Other(format!(
+				"Requested block range is too large: {} blocks, maximum is {}",
+				span.saturating_add(1),
+				MAX_BLOCK_STATS_RANGE,
+			)))
+		}
+		let compression = compression.unwrap_or_else(Compression::defaults);
+		let mut out = Vec::new();
+		// The previously processed block (hash + pre-execution state root) so the
+		// parent of the next block in the range is already in hand.
+		let mut previous: Option<(Block::Hash, Block::Hash)> = None;
+		let mut number = from;
+		while number <= to {
+			let hash = match self.client.hash(number).map_err(client_err)? {
+				Some(hash) => hash,
+				None => break,
+			};
+			let block = match self.client.block(&BlockId::Hash(hash)).map_err(client_err)? {
+				Some(block) => block.block,
+				None => break,
+			};
+			let parent_hash = *block.header().parent_hash();
+			// Reuse the parent's state root if it is the block we just processed,
+			// otherwise fall back to loading the parent (first iteration or a gap).
+			let pre_root = match previous {
+				Some((prev_hash, prev_root)) if prev_hash == parent_hash => prev_root,
+				_ => match self.client.block(&BlockId::Hash(parent_hash)).map_err(client_err)? {
+					Some(parent_block) => *parent_block.block.header().state_root(),
+					None => break,
+				},
+			};
+			let state_root = *block.header().state_root();
+			let stats = Self::compute_stats(&self.client, block, pre_root, &compression)?;
+			out.push((hash, stats));
+			previous = Some((hash, state_root));
+			number += One::one();
+		}
+		Ok(out)
+	}
+
+	fn subscribe_block_stats(
+		&self,
+		_metadata: crate::Metadata,
+		subscriber: Subscriber<BlockStats>,
+	) {
+		let client = self.client.clone();
+		// Recompute the stats for every newly imported best block. Notifications
+		// that pile up while the subscriber is busy are collapsed to the newest
+		// one, so a subscriber that falls behind drops frames instead of blocking
+		// the import pipeline, and stats are only computed for frames sent.
+		let stream = self
+			.client
+			.import_notification_stream()
+			.filter(|notification| future::ready(notification.is_new_best))
+			.ready_chunks(64)
+			.filter_map(|mut pending| future::ready(pending.pop()))
+			.filter_map(move |notification| {
+				let stats =
+					Self::stats_for_block(&client, notification.hash, &Compression::defaults());
+				future::ready(match stats {
+					Ok(Some(stats)) => Some(Ok::<_, ()>(Ok(stats))),
+					Ok(None) => None,
+					Err(err) => {
+						warn!(
+							target: "rpc",
+							"Failed to compute block stats for {:?}: {:?}",
+							notification.hash, err,
+						);
+						None
+					},
+				})
+			})
+			.compat();
+
+		self.subscriptions.add(subscriber, move |sink| {
+			sink.sink_map_err(|e| warn!("Error sending block stats notifications: {:?}", e))
+				.send_all(stream)
+				.map(|_| ())
+		});
+	}
+
+	fn subscribe_filtered_heads(
+		&self,
+		_metadata: crate::Metadata,
+		subscriber: Subscriber<Block::Header>,
+		filter: HeadFilter,
+	) {
+		let client = self.client.clone();
+		let stream = self
+			.client
+			.import_notification_stream()
+			.filter(move |notification| {
+				let matches = notification.is_new_best &&
+					Self::matches_filter(&client, &notification.header, &filter);
+				future::ready(matches)
+			})
+			.map(|n| Ok::<_, ()>(Ok(n.header)))
+			.compat();
+
+		self.subscriptions.add(subscriber, move |sink| {
+			sink.sink_map_err(|e| warn!("Error sending filtered head notifications: {:?}", e))
+				.send_all(stream)
+				.map(|_| ())
+		});
+	}
+}
+
+impl<Block, Client> FullChain<Block, Client>
+where
+	Block: BlockT + 'static,
+	Client: BlockBackend<Block> + HeaderBackend<Block> + ProvideRuntimeApi<Block> + 'static,
+	Client::Api: Core<Block>,
+{
+	/// Test whether `header` satisfies every set field of `filter`.
+	///
+	/// The extrinsic-count predicate needs the block body, which is fetched
+	/// lazily only when `min_extrinsics` is set; a fetch failure drops the block
+	/// from the stream rather than aborting the subscription.
+	pub(crate) fn matches_filter(
+		client: &Arc<Client>,
+		header: &Block::Header,
+		filter: &HeadFilter,
+	) -> bool {
+		let number: u64 = (*header.number()).unique_saturated_into();
+		if let Some(from) = filter.from {
+			if number < from.into_u256().low_u64() {
+				return false
+			}
+		}
+		if let Some(to) = filter.to {
+			if number > to.into_u256().low_u64() {
+				return false
 			}
+		}
+		if let Some(min) = filter.min_extrinsics {
+			match client.block(&BlockId::Hash(header.hash())) {
+				Ok(Some(block)) =>
+					if (block.block.extrinsics().len() as u64) < min {
+						return false
+					},
+				_ => return false,
+			}
+		}
+		true
+	}
+
+	/// Compute the execution statistics for the block identified by `hash`.
+	///
+	/// Returns `Ok(None)` when either the block or its parent is not available
+	/// locally, mirroring the polled [`ChainBackend::block_stats`] entry point.
+	///
+	/// The compacted witness is measured against every codec in `compression`;
+	/// `witness_compressed_len` reports the default codec for convenience, or the
+	/// first requested codec when the default is not among them.
+	fn stats_for_block(
+		client: &Arc<Client>,
+		hash: Block::Hash,
+		compression: &[Compression],
+	) -> Result<Option<BlockStats>> {
+		let block = match client.block(&BlockId::Hash(hash)).map_err(client_err)? {
+			Some(block) => block.block,
+			None => return Ok(None),
 		};
-		let parent_block = {
+		let pre_root = {
 			let parent_hash = *block.header().parent_hash();
-			let parent_block =
-				self.client.block(&BlockId::Hash(parent_hash)).map_err(client_err)?;
-			if let Some(parent_block) = parent_block {
-				parent_block.block
-			} else {
-				return Ok(None)
+			match client.block(&BlockId::Hash(parent_hash)).map_err(client_err)? {
+				Some(parent_block) => *parent_block.block.header().state_root(),
+				None => return Ok(None),
 			}
 		};
+		Self::compute_stats(client, block, pre_root, compression).map(Some)
+	}
+
+	/// Compute the statistics for an already-fetched `block` whose parent had
+	/// the pre-execution state root `pre_root`.
+	///
+	/// Splitting this out lets [`Self::block_stats_range`] carry the parent's
+	/// state root forward instead of re-fetching it for every block in a range.
+	fn compute_stats(
+		client: &Arc<Client>,
+		block: Block,
+		pre_root: Block::Hash,
+		compression: &[Compression],
+	) -> Result<BlockStats> {
 		let block_len = block.encoded_size() as u64;
 		let block_num_extrinsics = block.extrinsics().len() as u64;
-		let pre_root = *parent_block.header().state_root();
-		let parent_hash = block.header().parent_hash();
-		let mut runtime_api = self.client.runtime_api();
+		let parent_hash = *block.header().parent_hash();
+		let mut runtime_api = client.runtime_api();
 		runtime_api.record_proof();
 		runtime_api
-			.execute_block(&BlockId::Hash(*parent_hash), block)
+			.execute_block(&BlockId::Hash(parent_hash), block)
 			.map_err(|err| Error::Client(Box::new(err)))?;
 		let witness = runtime_api
 			.extract_proof()
@@ -122,14 +316,25 @@ where
 			.into_compact_proof::<HasherOf<Block>>(pre_root)
 			.map_err(|err| Error::Client(Box::new(err)))?
 			.encode();
-		let witness_compressed = zstd::stream::encode_all(&witness_compact[..], 0)
-			.map_err(|err| Error::Client(Box::new(err)))?;
-		Ok(Some(BlockStats {
+		let mut witness_compressed = Vec::new();
+		for codec in compression {
+			witness_compressed.push((*codec, codec.compressed_len(&witness_compact)?));
+		}
+		// Headline figure: prefer the default codec, else the first requested
+		// codec so the choice is deterministic rather than arbitrary.
+		let witness_compressed_len = witness_compressed
+			.iter()
+			.find(|(codec, _)| *codec == Compression::default())
+			.or_else(|| witness_compressed.first())
+			.map(|(_, len)| *len)
+			.unwrap_or(witness_compact.len() as u64);
+		Ok(BlockStats {
 			witness_len,
 			witness_compact_len: witness_compact.len() as u64,
-			witness_compressed_len: witness_compressed.len() as u64,
+			witness_compressed_len,
+			witness_compressed,
 			block_len,
 			block_num_extrinsics,
-		}))
+		})
 	}
 }
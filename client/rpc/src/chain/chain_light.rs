@@ -0,0 +1,230 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2019-2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Blockchain API backend for light nodes.
+//!
+//! A light node cannot execute blocks with proof recording, so it cannot
+//! compute [`BlockStats`] locally. Instead it proxies the query to a trusted
+//! full node over RPC and cross-checks the answer against the header it already
+//! holds, giving light clients parity on the chain RPC surface.
+
+use super::{
+	client_err,
+	error::{Error, FutureResult, Result},
+	BlockStats, ChainBackend, Compression,
+};
+use futures::FutureExt;
+use jsonrpc_pubsub::{manager::SubscriptionManager, typed::Subscriber};
+use jsonrpsee::{core::client::ClientT, rpc_params, ws_client::WsClient};
+use sc_client_api::{BlockBackend, BlockchainEvents};
+use sp_blockchain::HeaderBackend;
+use sp_core::Encode;
+use sp_runtime::{
+	generic::{BlockId, SignedBlock},
+	traits::{Block as BlockT, Header, NumberFor},
+};
+use std::{marker::PhantomData, sync::Arc};
+
+use jsonrpc_core as rpc;
+
+/// Blockchain API backend for light nodes. Answers `block_stats` by forwarding
+/// to a trusted full node and validating the result locally.
+pub struct LightChain<Block: BlockT, Client> {
+	/// Substrate client.
+	client: Arc<Client>,
+	/// Current subscriptions.
+	subscriptions: SubscriptionManager,
+	/// Trusted full node used to answer queries this node cannot compute.
+	remote: Arc<WsClient>,
+	/// phantom member to pin the block type
+	_phantom: PhantomData<Block>,
+}
+
+impl<Block: BlockT, Client> LightChain<Block, Client> {
+	/// Create new Chain API RPC handler backed by the trusted `remote` full node.
+	pub fn new(
+		client: Arc<Client>,
+		subscriptions: SubscriptionManager,
+		remote: Arc<WsClient>,
+	) -> Self {
+		Self { client, subscriptions, remote, _phantom: PhantomData }
+	}
+}
+
+impl<Block, Client> ChainBackend<Client, Block> for LightChain<Block, Client>
+where
+	Block: BlockT + 'static,
+	Block::Header: Unpin,
+	Client: BlockBackend<Block> + HeaderBackend<Block> + BlockchainEvents<Block> + 'static,
+{
+	fn client(&self) -> &Arc<Client> {
+		&self.client
+	}
+
+	fn subscriptions(&self) -> &SubscriptionManager {
+		&self.subscriptions
+	}
+
+	fn header(&self, hash: Option<Block::Hash>) -> FutureResult<Option<Block::Header>> {
+		let res = self.client.header(BlockId::Hash(self.unwrap_or_best(hash))).map_err(client_err);
+		async move { res }.boxed()
+	}
+
+	fn block(&self, hash: Option<Block::Hash>) -> FutureResult<Option<SignedBlock<Block>>> {
+		let res = self.client.block(&BlockId::Hash(self.unwrap_or_best(hash))).map_err(client_err);
+		async move { res }.boxed()
+	}
+
+	fn block_stats(
+		&self,
+		hash: Option<Block::Hash>,
+		compression: Option<Vec<Compression>>,
+	) -> Result<Option<BlockStats>> {
+		let hash = self.unwrap_or_best(hash);
+		// Only answer for blocks whose header we already trust locally.
+		let header = match self.client.header(BlockId::Hash(hash)).map_err(client_err)? {
+			Some(header) => header,
+			None => return Ok(None),
+		};
+		let remote = self.remote.clone();
+		// NOTE: the `ChainBackend` trait is synchronous, so the remote round-trip
+		// is driven to completion here with `block_on`. This parks the calling RPC
+		// worker thread for the duration of the request; callers should expect a
+		// light-node `block_stats` to take as long as one full-node round-trip.
+		let stats: Option<BlockStats> = futures::executor::block_on(async move {
+			remote.request("chain_blockStats", rpc_params![hash, compression]).await
+		})
+		.map_err(|err| Error::Client(Box::new(err)))?;
+		if let Some(ref stats) = stats {
+			Self::validate_against_header(&self.client, hash, &header, stats)?;
+		}
+		Ok(stats)
+	}
+
+	fn block_stats_range(
+		&self,
+		from: NumberFor<Block>,
+		to: NumberFor<Block>,
+		compression: Option<Vec<Compression>>,
+	) -> Result<Vec<(Block::Hash, BlockStats)>> {
+		let remote = self.remote.clone();
+		// As in `block_stats`, the synchronous trait forces us to block the calling
+		// worker thread on the remote round-trip.
+		let range: Vec<(Block::Hash, BlockStats)> = futures::executor::block_on(async move {
+			remote.request("chain_blockStatsRange", rpc_params![from, to, compression]).await
+		})
+		.map_err(|err| Error::Client(Box::new(err)))?;
+		for (hash, stats) in &range {
+			if let Some(header) = self.client.header(BlockId::Hash(*hash)).map_err(client_err)? {
+				Self::validate_against_header(&self.client, *hash, &header, stats)?;
+			}
+		}
+		Ok(range)
+	}
+
+	fn subscribe_block_stats(
+		&self,
+		_metadata: crate::Metadata,
+		subscriber: Subscriber<BlockStats>,
+	) {
+		// A light node has no import pipeline to recompute stats from.
+		let _ = subscriber.reject(rpc::Error {
+			code: rpc::ErrorCode::MethodNotFound,
+			message: "Block stats subscriptions are not available on light clients".into(),
+			data: None,
+		});
+	}
+
+	fn subscribe_filtered_heads(
+		&self,
+		_metadata: crate::Metadata,
+		subscriber: Subscriber<Block::Header>,
+		_filter: super::HeadFilter,
+	) {
+		// Filtering on extrinsic counts needs block bodies the light node does
+		// not keep, so this is only served by full nodes.
+		let _ = subscriber.reject(rpc::Error {
+			code: rpc::ErrorCode::MethodNotFound,
+			message: "Filtered head subscriptions are not available on light clients".into(),
+			data: None,
+		});
+	}
+}
+
+impl<Block, Client> LightChain<Block, Client>
+where
+	Block: BlockT + 'static,
+	Client: BlockBackend<Block> + HeaderBackend<Block> + 'static,
+{
+	/// Reject a remotely supplied [`BlockStats`] that is either internally
+	/// inconsistent or disagrees with data we hold locally.
+	///
+	/// The header does not carry the witness sizes, so the strongest header-level
+	/// check is that the locally held header is the one for the requested hash and
+	/// that the compacted witness is no larger than the raw one (`compact <= raw`).
+	/// Compressed lengths are deliberately *not* bounded by the compacted length:
+	/// zstd/brotli add frame and header overhead, so for small or incompressible
+	/// proofs a compressed figure legitimately exceeds the compacted one. When the
+	/// full body happens to be available locally (e.g. a cached block) we also
+	/// cross-check its length and extrinsic count.
+	fn validate_against_header(
+		client: &Arc<Client>,
+		hash: Block::Hash,
+		header: &Block::Header,
+		stats: &BlockStats,
+	) -> Result<()> {
+		// Guard against the header and requested hash drifting apart.
+		if header.hash() != hash {
+			return Err(Error::Other(format!(
+				"Remote block stats header hash {:?} does not match requested {:?}",
+				header.hash(),
+				hash,
+			)))
+		}
+		if !stats_are_internally_consistent(stats) {
+			return Err(Error::Other(format!(
+				"Remote block stats for {:?} are internally inconsistent",
+				hash,
+			)))
+		}
+		// If we happen to hold the body locally, the full node's block-level
+		// figures must match it exactly.
+		if let Some(block) = client.block(&BlockId::Hash(hash)).map_err(client_err)? {
+			let block_len = block.block.encoded_size() as u64;
+			let block_num_extrinsics = block.block.extrinsics().len() as u64;
+			if stats.block_len != block_len || stats.block_num_extrinsics != block_num_extrinsics {
+				return Err(Error::Other(format!(
+					"Remote block stats for {:?} do not match local block",
+					hash,
+				)))
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Whether a [`BlockStats`] is self-consistent enough to trust from a remote.
+///
+/// Compaction can only shrink the witness, so `witness_compact_len` must not
+/// exceed `witness_len`. Compressed lengths are deliberately left unbounded:
+/// zstd/brotli add framing overhead, so on small or incompressible proofs a
+/// compressed figure legitimately exceeds the compacted one and must not be
+/// treated as inconsistent.
+pub(crate) fn stats_are_internally_consistent(stats: &BlockStats) -> bool {
+	stats.witness_compact_len <= stats.witness_len
+}
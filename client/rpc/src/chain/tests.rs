@@ -0,0 +1,174 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2019-2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use crate::testing::TaskExecutor;
+use sc_block_builder::BlockBuilderProvider;
+use substrate_test_runtime_client::{
+	prelude::*,
+	runtime::Block,
+	sp_consensus::BlockOrigin,
+};
+
+type TestClient = substrate_test_runtime_client::TestClient;
+
+fn test_client() -> Arc<TestClient> {
+	Arc::new(substrate_test_runtime_client::new())
+}
+
+fn full_chain(client: Arc<TestClient>) -> FullChain<Block, TestClient> {
+	FullChain::new(client, SubscriptionManager::new(Arc::new(TaskExecutor)))
+}
+
+/// Import `count` empty blocks on top of the current best, returning their
+/// hashes in import order.
+fn import_empty_blocks(client: &Arc<TestClient>, count: usize) -> Vec<<Block as BlockT>::Hash> {
+	let mut hashes = Vec::new();
+	for _ in 0..count {
+		let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
+		let hash = block.hash();
+		futures::executor::block_on(client.import(BlockOrigin::Own, block)).unwrap();
+		hashes.push(hash);
+	}
+	hashes
+}
+
+#[test]
+fn block_stats_range_walks_contiguously_and_matches_per_block() {
+	let client = test_client();
+	let hashes = import_empty_blocks(&client, 3);
+	let chain = full_chain(client);
+
+	let range = chain.block_stats_range(1, 3, None).unwrap();
+	// The walk yields exactly the imported range, in order, reusing each block
+	// as the next one's parent instead of refetching.
+	assert_eq!(range.iter().map(|(hash, _)| *hash).collect::<Vec<_>>(), hashes);
+	// Carrying the parent state root forward must not change the numbers: every
+	// entry equals the independent single-block computation.
+	for (hash, stats) in range {
+		let single = chain.block_stats(Some(hash), None).unwrap().unwrap();
+		assert_eq!(single, stats);
+	}
+}
+
+#[test]
+fn block_stats_range_breaks_early_past_the_chain_tip() {
+	let client = test_client();
+	let hashes = import_empty_blocks(&client, 2);
+	let chain = full_chain(client);
+
+	// Asking beyond the tip stops at the last known block rather than erroring.
+	let range = chain.block_stats_range(1, 5, None).unwrap();
+	assert_eq!(range.iter().map(|(hash, _)| *hash).collect::<Vec<_>>(), hashes);
+}
+
+#[test]
+fn block_stats_range_rejects_inverted_bounds() {
+	let client = test_client();
+	import_empty_blocks(&client, 2);
+	let chain = full_chain(client);
+
+	assert!(chain.block_stats_range(2, 1, None).is_err());
+}
+
+#[test]
+fn matches_filter_applies_number_bounds_and_extrinsic_floor() {
+	let client = test_client();
+	let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
+	let header = block.header().clone();
+	futures::executor::block_on(client.import(BlockOrigin::Own, block)).unwrap();
+
+	// An empty filter forwards every head.
+	assert!(FullChain::<Block, TestClient>::matches_filter(
+		&client,
+		&header,
+		&HeadFilter::default(),
+	));
+	// The block is number 1, so a lower bound of 2 excludes it.
+	let filter = HeadFilter { from: Some(2u64.into()), ..Default::default() };
+	assert!(!FullChain::<Block, TestClient>::matches_filter(&client, &header, &filter));
+	// Empty blocks fall below any positive extrinsic floor.
+	let filter = HeadFilter { min_extrinsics: Some(1), ..Default::default() };
+	assert!(!FullChain::<Block, TestClient>::matches_filter(&client, &header, &filter));
+}
+
+#[test]
+fn compression_reports_a_length_for_every_codec() {
+	let data = vec![7u8; 4096];
+	assert_eq!(Compression::None.compressed_len(&data).unwrap(), data.len() as u64);
+	assert!(Compression::Zstd { level: 3 }.compressed_len(&data).unwrap() > 0);
+	assert!(Compression::Brotli { quality: 5, window: 22 }.compressed_len(&data).unwrap() > 0);
+}
+
+#[test]
+fn witness_compressed_keeps_an_entry_per_codec_variant() {
+	let data = vec![0u8; 1024];
+	let codecs = [Compression::Zstd { level: 1 }, Compression::Zstd { level: 19 }];
+	let witness_compressed: Vec<(Compression, u64)> =
+		codecs.iter().map(|c| (*c, c.compressed_len(&data).unwrap())).collect();
+	// Two zstd codecs differing only in level must not collide into one entry.
+	assert_eq!(witness_compressed.len(), 2);
+}
+
+#[test]
+fn block_stats_round_trips_through_json() {
+	// `Compression` serializes to a JSON object, so it cannot be a JSON map key;
+	// `witness_compressed` must stay a list for the response to serialize.
+	let stats = BlockStats {
+		witness_len: 100,
+		witness_compact_len: 80,
+		witness_compressed_len: 60,
+		witness_compressed: vec![
+			(Compression::Zstd { level: 3 }, 60),
+			(Compression::Brotli { quality: 5, window: 22 }, 58),
+		],
+		block_len: 256,
+		block_num_extrinsics: 4,
+	};
+	let json = serde_json::to_string(&stats).unwrap();
+	let decoded: BlockStats = serde_json::from_str(&json).unwrap();
+	assert_eq!(decoded, stats);
+}
+
+#[test]
+fn consistency_allows_compressed_exceeding_compact() {
+	// zstd/brotli framing can make the compressed figure larger than the
+	// compacted one on tiny proofs; that is valid, not inconsistent.
+	let stats = BlockStats {
+		witness_len: 10,
+		witness_compact_len: 8,
+		witness_compressed_len: 20,
+		witness_compressed: Vec::new(),
+		block_len: 0,
+		block_num_extrinsics: 0,
+	};
+	assert!(super::chain_light::stats_are_internally_consistent(&stats));
+}
+
+#[test]
+fn consistency_rejects_compact_exceeding_raw() {
+	let stats = BlockStats {
+		witness_len: 8,
+		witness_compact_len: 10,
+		witness_compressed_len: 5,
+		witness_compressed: Vec::new(),
+		block_len: 0,
+		block_num_extrinsics: 0,
+	};
+	assert!(!super::chain_light::stats_are_internally_consistent(&stats));
+}